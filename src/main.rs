@@ -1,17 +1,49 @@
 use log::*;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use tether::MenuItem;
+
+thread_local! {
+    // Keeps the app running after the main window is closed, to finish
+    // downloads and such.
+    static TRAY: RefCell<Option<tether::Tray>> = const { RefCell::new(None) };
+    // Which window is ours, rather than a page's popup.
+    static MAIN_WINDOW: Cell<Option<tether::WindowId>> = const { Cell::new(None) };
+}
 
 struct Handler {}
 
 impl Drop for Handler {
     fn drop(&mut self) {
-        info!("Exiting");
-        tether::exit();
+        let in_tray = TRAY.with(|tray| {
+            tray.borrow()
+                .as_ref()
+                .is_some_and(tether::Tray::is_embedded)
+        });
+        if in_tray {
+            info!("Main window closed, still running in the tray");
+            return;
+        }
+
+        // Some desktops (like GNOME) don't show tray icons, so there'd be no
+        // way to quit. Go once the popups are closed too.
+        if tether::windows().is_empty() {
+            info!("Main window closed, and there's no tray icon, exiting");
+            tether::exit();
+        } else {
+            info!("Main window closed, and there's no tray icon, exiting after the popups");
+            tether::set_exit_on_last_window_closed(true);
+        }
     }
 }
 
 impl tether::Handler for Handler {
-    fn handle_rpc(&mut self, _window: tether::Window, msg: &str) {
-        info!("[rpc] received {}", msg);
+    fn handle_rpc(&mut self, _window: tether::Window, origin: &str, msg: &str) {
+        info!("[rpc] received {} from {}", msg, origin);
+    }
+
+    fn handle_frame_rpc(&mut self, _window: tether::Window, frame: &str, origin: &str, msg: &str) {
+        info!("[rpc] received {} from frame {:?} ({})", msg, frame, origin);
     }
 
     fn handle_net(&mut self, req: tether::NetRequest) -> Result<(), Box<dyn std::error::Error>> {
@@ -54,6 +86,282 @@ impl tether::Handler for Handler {
 
         Ok(())
     }
+
+    fn close_requested(&mut self, window: tether::Window) -> bool {
+        save_geometry(&window);
+        true
+    }
+
+    fn menu_activated(&mut self, window: tether::Window, id: &str) {
+        match id {
+            "file.sideload" => {
+                let mut dialog = tether::FileDialog::new(tether::DialogKind::Open);
+                dialog.title = Some("Sideload a game".into());
+                dialog.filters = vec![
+                    tether::FileFilter::new("Game archives", &["*.zip", "*.7z", "*.tar.gz"]),
+                    tether::FileFilter::new("All files", &["*"]),
+                ];
+                dialog.show(Some(&window), |paths| sideload(&paths));
+            }
+            "file.quit" => quit(),
+            "library.refresh" => window.load(include_str!("./resources/index.html")),
+            "library.inspector" => window.show_inspector(),
+            "library.fullscreen" => {
+                let fullscreen = window.state().is_some_and(|state| state.fullscreen);
+                window.set_fullscreen(!fullscreen);
+            }
+            _ => info!("[menu] {} isn't implemented yet", id),
+        }
+    }
+
+    fn dropped(&mut self, _window: tether::Window, paths: &[PathBuf], _x: isize, _y: isize) {
+        sideload(paths);
+    }
+
+    fn decide_navigation(
+        &mut self,
+        _window: tether::Window,
+        navigation: &tether::Navigation,
+    ) -> tether::NavigationPolicy {
+        let url = &navigation.url;
+        let ours = url.as_str() == "about:blank" || url.host_str() == Some("itch-lite");
+        match navigation.main_frame {
+            // Our own pages, and embedded games going wherever they like.
+            _ if ours => tether::NavigationPolicy::Allow,
+            Some(false) => tether::NavigationPolicy::Allow,
+            Some(true) if navigation.user_initiated => {
+                info!("[navigation] opening {} in the browser", url);
+                tether::NavigationPolicy::OpenExternal
+            }
+            // Linux doesn't say which frame is navigating, so a click could
+            // just as well be inside a game's iframe: let it go where it was
+            // going rather than off to the browser. Nobody clicked for the
+            // rest, so they stay put.
+            None if navigation.user_initiated => tether::NavigationPolicy::Allow,
+            Some(true) | None => {
+                warn!("[navigation] not going to {}", url);
+                tether::NavigationPolicy::Deny
+            }
+        }
+    }
+
+    fn load_finished(&mut self, _window: tether::Window, url: &str) {
+        info!("[load] finished {}", url);
+    }
+
+    fn load_failed(&mut self, _window: tether::Window, url: &str, error: &str) {
+        warn!("[load] could not load {}: {}", url, error);
+    }
+
+    fn download_requested(
+        &mut self,
+        _window: tether::Window,
+        request: &tether::DownloadRequest,
+    ) -> tether::DownloadPolicy {
+        // Only keep the last component of whatever name we were given.
+        let name = request
+            .suggested_file_name
+            .as_deref()
+            .and_then(|name| Path::new(name).file_name())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(format!("download-{}", request.id)));
+        match tether::downloads_dir() {
+            Some(dir) => {
+                let path = unused_path(&dir, &name);
+                info!("[download] saving {} to {:?}", request.url, path);
+                tether::DownloadPolicy::SaveTo(path)
+            }
+            None => {
+                warn!("[download] nowhere to save {}", request.url);
+                tether::DownloadPolicy::Cancel
+            }
+        }
+    }
+
+    fn download_progressed(
+        &mut self,
+        _window: tether::Window,
+        download: tether::DownloadId,
+        received: u64,
+        size: Option<u64>,
+    ) {
+        match size {
+            Some(size) => debug!("[download] {}: {}/{} bytes", download, received, size),
+            None => debug!("[download] {}: {} bytes", download, received),
+        }
+    }
+
+    fn download_finished(&mut self, _window: tether::Window, download: tether::DownloadId) {
+        info!("[download] {} finished", download);
+        tether::Notification::new("Download finished")
+            .with_icon(icon())
+            .show(NotificationHandler {});
+    }
+
+    fn context_menu(
+        &mut self,
+        _window: tether::Window,
+        hit: &tether::HitTest,
+        menu: &mut tether::ContextMenu,
+    ) {
+        menu.remove_navigation();
+        if hit.link_url.is_some() {
+            menu.push(MenuItem::new("context.install", "Install"));
+        }
+        if hit.selected_text.is_some() {
+            menu.push(MenuItem::new("context.copy-key", "Copy key"));
+        }
+        menu.push(MenuItem::new("context.open-folder", "Open folder"));
+    }
+
+    fn context_menu_activated(&mut self, _window: tether::Window, id: &str, hit: &tether::HitTest) {
+        match id {
+            "context.install" => {
+                if let Some(url) = &hit.link_url {
+                    info!("[context] would install {}", url);
+                }
+            }
+            "context.copy-key" => {
+                if let Some(key) = &hit.selected_text {
+                    tether::clipboard::set_text(key.trim());
+                }
+            }
+            "context.open-folder" => match tether::downloads_dir() {
+                Some(dir) => tether::open_path(dir),
+                None => warn!("[context] no folder to open"),
+            },
+            _ => info!("[context] {} isn't implemented yet", id),
+        }
+    }
+
+    fn download_failed(
+        &mut self,
+        _window: tether::Window,
+        download: tether::DownloadId,
+        error: &str,
+    ) {
+        warn!("[download] {} failed: {}", download, error);
+    }
+
+    fn new_window_requested(
+        &mut self,
+        _window: tether::Window,
+        request: &tether::NewWindowRequest,
+    ) -> tether::NewWindowPolicy {
+        info!("[popup] opening {}", request.url);
+        let (width, height) = request.size.unwrap_or((600, 700));
+        tether::NewWindowPolicy::Open(tether::Options {
+            initial_width: width,
+            initial_height: height,
+            initial_position: request.position,
+            icon: Some(icon()),
+            handler: Some(Box::new(PopupHandler {})),
+            ..Default::default()
+        })
+    }
+}
+
+/// Handles windows opened by pages, like sign-in and payment popups, which
+/// can go wherever they like.
+struct PopupHandler {}
+
+impl tether::Handler for PopupHandler {}
+
+struct NotificationHandler {}
+
+impl tether::NotificationHandler for NotificationHandler {
+    fn activated(&mut self, _action: Option<&str>) {
+        show_main_window();
+    }
+}
+
+struct TrayHandler {}
+
+impl tether::TrayHandler for TrayHandler {
+    fn activated(&mut self) {
+        show_main_window();
+    }
+
+    fn menu_activated(&mut self, id: &str) {
+        match id {
+            "tray.show" => show_main_window(),
+            "tray.quit" => quit(),
+            _ => info!("[tray] {} isn't implemented yet", id),
+        }
+    }
+}
+
+fn sideload(paths: &[PathBuf]) {
+    for path in paths {
+        info!("[sideload] would install {:?}", path);
+    }
+    if let [path] = paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        tether::Notification::new("Sideload queued")
+            .with_body(format!("{} will be installed shortly.", name))
+            .with_icon(icon())
+            .show(NotificationHandler {});
+    }
+}
+
+fn quit() {
+    info!("Exiting");
+    // Exiting doesn't ask the windows' handlers first.
+    if let Some(window) = main_window() {
+        save_geometry(&window);
+    }
+    tether::exit();
+}
+
+fn save_geometry(window: &tether::Window) {
+    if let (Some(geometry), Some(path)) = (window.geometry(), geometry_path()) {
+        info!("saving window geometry to {:?}", path);
+        if let Err(e) = geometry.save(&path) {
+            warn!("could not save window geometry: {}", e);
+        }
+    }
+}
+
+/// Where the main window's geometry is remembered between runs.
+fn geometry_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join("Library")
+                .join("Application Support")
+        })
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("itch-lite").join("window-geometry"))
+}
+
+/// Returns where to save a file called `name` in `dir` without replacing
+/// anything, like `name (1).zip` if `name.zip` is taken.
+fn unused_path(dir: &Path, name: &Path) -> PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+
+    let name = name.to_string_lossy();
+    // Keep `.tar.gz` and friends together.
+    let split = match name.rfind(".tar.") {
+        Some(i) if i > 0 => i,
+        _ => match name.rfind('.') {
+            Some(i) if i > 0 => i,
+            _ => name.len(),
+        },
+    };
+    let (stem, extension) = name.split_at(split);
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
 }
 
 fn main() {
@@ -68,15 +376,83 @@ fn main() {
 }
 
 fn start() {
-    let win = tether::Window::new(tether::Options {
-        debug: true,
+    if let Some(port) = std::env::var("ITCH_LITE_INSPECTOR_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+    {
+        info!("remote inspector on port {}", port);
+        tether::enable_remote_inspector(port);
+    }
+
+    let tray = tether::Tray::new(TrayHandler {});
+    tray.set_icon(&icon());
+    tray.set_tooltip("itch lite");
+    tray.set_menu(&[
+        MenuItem::new("tray.show", "Show itch lite"),
+        MenuItem::Separator,
+        MenuItem::new("tray.quit", "Quit"),
+    ]);
+    TRAY.with(|cell| cell.replace(Some(tray)));
+
+    show_main_window();
+}
+
+fn icon() -> tether::Icon {
+    tether::Icon::from_png(&include_bytes!("./resources/icon.png")[..])
+}
+
+fn main_window() -> Option<tether::Window> {
+    MAIN_WINDOW.with(Cell::get).and_then(tether::window)
+}
+
+fn show_main_window() {
+    if let Some(window) = main_window() {
+        window.focus();
+        return;
+    }
+
+    let mut opts = tether::Options {
+        developer_extras: true,
         initial_width: 1280,
         initial_height: 720,
+        trusted_origins: vec!["http://itch-lite".into()],
+        icon: Some(icon()),
+        drop_navigation: false,
         handler: Some(Box::new(Handler {})),
         ..Default::default()
-    });
+    };
+
+    if let Some(path) = geometry_path() {
+        match tether::Geometry::load(&path) {
+            Ok(geometry) => geometry.clamp(&tether::monitors()).apply(&mut opts),
+            Err(e) => info!("not restoring window geometry from {:?}: {}", path, e),
+        }
+    }
+
+    let win = tether::Window::new(opts);
+    MAIN_WINDOW.with(|id| id.set(Some(win.id())));
 
     win.title("itch lite");
+    win.set_menu(&[
+        MenuItem::submenu(
+            "File",
+            vec![
+                MenuItem::new("file.sideload", "Sideload Game…").with_accelerator("Primary+O"),
+                MenuItem::Separator,
+                MenuItem::new("file.quit", "Quit").with_accelerator("Primary+Q"),
+            ],
+        ),
+        MenuItem::submenu(
+            "Library",
+            vec![
+                MenuItem::new("library.refresh", "Refresh").with_accelerator("F5"),
+                MenuItem::Separator,
+                MenuItem::new("library.fullscreen", "Full Screen").with_accelerator("F11"),
+                MenuItem::new("library.inspector", "Developer Tools").with_accelerator("F12"),
+            ],
+        ),
+        MenuItem::submenu("Help", vec![MenuItem::new("help.about", "About itch lite")]),
+    ]);
     // win.navigate("http://itch-lite/index.html");
     win.load(include_str!("./resources/index.html"));
 }